@@ -1,11 +1,69 @@
 #![doc = include_str!("../README.md")]
 #![warn(missing_docs)]
+#[cfg(feature = "alloc-stats")]
+use core::alloc::{GlobalAlloc, Layout};
+#[cfg(all(feature = "tsc", target_arch = "x86_64"))]
+use core::arch::x86_64::{_mm_lfence, _rdtsc};
+use core::borrow::Borrow;
+#[cfg(feature = "alloc-stats")]
+use core::cell::Cell;
+use core::cmp::Reverse;
+use core::error::Error;
+use core::fmt::{self, Write as _};
+#[cfg(feature = "async")]
+use core::future::Future;
+use core::hash::Hash;
+use core::iter;
+#[cfg(all(
+    feature = "cpu-time",
+    any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd"
+    )
+))]
+use core::mem::MaybeUninit;
+#[cfg(feature = "async")]
+use core::pin::Pin;
+#[cfg(feature = "async")]
+use core::task::{Context, Poll};
 use core::time::Duration;
-use std::time::Instant;
+#[cfg(feature = "alloc-stats")]
+use std::alloc::System;
+use std::collections::HashMap;
+use std::io;
+use std::panic;
+use std::sync::OnceLock;
+use std::thread::{self, Thread};
+use std::time::{Instant, SystemTime};
+
+/// re-export of [`core::hint::black_box`] for use inside timed closures.
+///
+/// wrap inputs and outputs of the code under test so the optimizer can't
+/// see through or delete work whose result is otherwise unused:
+///
+/// ```rust
+/// use voxell_timer::{bench_fn, black_box};
+///
+/// let stats = bench_fn(10, || black_box(black_box(21_u64) * 2));
+/// assert_eq!(stats.runs, 10);
+/// ```
+pub use core::hint::black_box;
 
 /// use when you need both the result of the closure and the time
 /// it took to execute as a tuple.
+///
+/// discarding the returned tuple is almost always a mistake, so it is
+/// `#[must_use]`:
+///
+/// ```rust,compile_fail
+/// #![deny(unused_must_use)]
+/// voxell_timer::time_fn(|| 5 + 3);
+/// ```
 #[inline]
+#[must_use = "the measured duration is discarded; call the closure directly if you only need its result"]
 pub fn time_fn<T, F>(f: F) -> (T, Duration)
 where
     F: FnOnce() -> T,
@@ -16,34 +74,1958 @@ where
     (res, dur)
 }
 
-/// use for dirty debugging by printing the time it took to execute
+/// use when you need the start and end instants of the closure rather
+/// than just the time between them, e.g. to line up with other
+/// timestamped events.
+///
+/// ```rust
+/// use voxell_timer::time_fn_span;
+///
+/// let (res, start, end) = time_fn_span(|| 5 + 3);
+/// assert_eq!(res, 8);
+/// assert!(end >= start);
+/// ```
+#[inline]
+#[must_use = "the measured start and end instants are discarded; call the closure directly if you only need its result"]
+pub fn time_fn_span<T, F>(f: F) -> (T, Instant, Instant)
+where
+    F: FnOnce() -> T,
+{
+    let start = Instant::now();
+    let res = f();
+    let end = Instant::now();
+    (res, start, end)
+}
+
+/// use for quick "which one is fastest" races: runs every closure on its
+/// own thread at the same time and times each one.
+///
+/// returns each closure's result and wall time in input order, plus the
+/// index of the closure that finished first (`None` if `fs` is empty).
+/// a panic in any closure is propagated once all threads have finished.
+///
+/// ```rust
+/// use voxell_timer::time_all_parallel;
+///
+/// let data = (0..10_000).rev().collect::<Vec<u32>>();
+/// let tasks: Vec<Box<dyn FnOnce() -> u32 + Send>> = vec![
+///     Box::new(|| data.iter().copied().max().unwrap_or(0)),
+///     Box::new(|| {
+///         let mut v = data.clone();
+///         v.sort_unstable();
+///         v.last().copied().unwrap_or(0)
+///     }),
+/// ];
+///
+/// let (results, winner) = time_all_parallel(tasks);
+/// assert_eq!(results.len(), 2);
+/// assert!(results.iter().all(|&(max, _)| max == 9_999));
+/// assert!(winner.is_some());
+/// ```
+#[inline]
+#[must_use]
+pub fn time_all_parallel<T, F>(fs: Vec<F>) -> (Vec<(T, Duration)>, Option<usize>)
+where
+    F: FnOnce() -> T + Send,
+    T: Send,
+{
+    let finished = thread::scope(|scope| {
+        let handles = fs
+            .into_iter()
+            .map(|f| {
+                scope.spawn(|| {
+                    let (res, dur) = time_fn(f);
+                    (res, dur, Instant::now())
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+            .collect::<Vec<_>>()
+    });
+
+    let winner = finished
+        .iter()
+        .enumerate()
+        .min_by_key(|&(_, &(_, _, end))| end)
+        .map(|(i, _)| i);
+    let results = finished
+        .into_iter()
+        .map(|(res, dur, _)| (res, dur))
+        .collect();
+    (results, winner)
+}
+
+/// implement for types that know how to run themselves to get timing for
+/// free through [`Measurable::timed_run`].
+///
+/// ```rust
+/// use voxell_timer::Measurable;
+///
+/// struct SumJob(Vec<u64>);
+///
+/// impl Measurable for SumJob {
+///     type Output = u64;
+///
+///     fn run(self) -> u64 {
+///         self.0.iter().sum()
+///     }
+/// }
+///
+/// let (sum, _dur) = SumJob(vec![1, 2, 3]).timed_run();
+/// assert_eq!(sum, 6);
+/// ```
+pub trait Measurable: Sized {
+    /// what running the job produces.
+    type Output;
+
+    /// runs the job.
+    fn run(self) -> Self::Output;
+
+    /// runs the job through [`time_fn`], returning its output and the time
+    /// it took.
+    #[inline]
+    #[must_use = "the measured duration is discarded; call `run()` directly if you only need its output"]
+    fn timed_run(self) -> (Self::Output, Duration) {
+        time_fn(|| self.run())
+    }
+}
+
+/// a source of timestamps that [`time_fn_with_clock`] can measure with.
+pub trait Clock {
+    /// a point in time as reported by this clock.
+    type Timestamp: Copy;
+
+    /// the current time.
+    fn now(&self) -> Self::Timestamp;
+
+    /// the time between two timestamps, where `later` was taken after
+    /// `earlier`.
+    fn between(&self, earlier: Self::Timestamp, later: Self::Timestamp) -> Duration;
+}
+
+/// the monotonic [`Instant`] clock that [`time_fn`] uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MonotonicClock;
+
+impl Clock for MonotonicClock {
+    type Timestamp = Instant;
+
+    #[inline]
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    #[inline]
+    fn between(&self, earlier: Instant, later: Instant) -> Duration {
+        later.saturating_duration_since(earlier)
+    }
+}
+
+/// the wall clock, backed by [`SystemTime`].
+///
+/// unlike [`MonotonicClock`] this clock can be adjusted while a closure
+/// runs (NTP, manual changes, DST-unaware sources), so a measurement can
+/// come out shorter than the real elapsed time or even negative. negative
+/// deltas are saturated to zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    type Timestamp = SystemTime;
+
+    #[inline]
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    #[inline]
+    fn between(&self, earlier: SystemTime, later: SystemTime) -> Duration {
+        later.duration_since(earlier).unwrap_or_default()
+    }
+}
+
+/// like [`time_fn`], but measures with the given clock instead of
+/// [`Instant`].
+///
+/// ```rust
+/// use voxell_timer::{time_fn_with_clock, SystemClock};
+///
+/// let (res, _dur) = time_fn_with_clock(&SystemClock, || 5 + 3);
+/// assert_eq!(res, 8);
+/// ```
+#[inline]
+#[must_use = "the measured duration is discarded; call the closure directly if you only need its result"]
+pub fn time_fn_with_clock<C, T, F>(clock: &C, f: F) -> (T, Duration)
+where
+    C: Clock,
+    F: FnOnce() -> T,
+{
+    let start = clock.now();
+    let res = f();
+    let end = clock.now();
+    (res, clock.between(start, end))
+}
+
+/// use to tell "slow because blocked" from "slow because busy": returns
+/// the closure's result, its wall time and the CPU time the current thread
+/// spent running it.
+///
+/// a CPU time well below the wall time points at I/O, sleeping or lock
+/// contention.
+///
+/// CPU time is read from the thread CPU clock, which needs the `cpu-time`
+/// feature on Linux, Android, macOS, iOS or FreeBSD. everywhere else the
+/// CPU time is just a copy of the wall time.
+///
+/// ```rust
+/// use voxell_timer::time_fn_both;
+///
+/// let (res, _wall, _cpu) = time_fn_both(|| (0..1000).sum::<u64>());
+/// assert_eq!(res, 499_500);
+/// ```
+#[inline]
+#[must_use = "the measured durations are discarded"]
+pub fn time_fn_both<T, F>(f: F) -> (T, Duration, Duration)
+where
+    F: FnOnce() -> T,
+{
+    let cpu_start = thread_cpu_time();
+    let (res, wall) = time_fn(f);
+    let cpu = match (cpu_start, thread_cpu_time()) {
+        (Some(start), Some(end)) => end.saturating_sub(start),
+        _ => wall,
+    };
+    (res, wall, cpu)
+}
+
+/// CPU time consumed by the current thread so far, if the platform and
+/// enabled features can tell.
+#[cfg(all(
+    feature = "cpu-time",
+    any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd"
+    )
+))]
+fn thread_cpu_time() -> Option<Duration> {
+    // zeroed rather than a struct literal, since some targets add private
+    // padding fields to `timespec`
+    let mut ts = MaybeUninit::<libc::timespec>::zeroed();
+    // SAFETY: `ts` is valid, writable storage for a timespec.
+    let ret = unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, ts.as_mut_ptr()) };
+    if ret != 0 {
+        return None;
+    }
+    // SAFETY: all-zero bytes are a valid timespec, and `clock_gettime` just
+    // filled it in.
+    let ts = unsafe { ts.assume_init() };
+    let secs = u64::try_from(ts.tv_sec).ok()?;
+    let nanos = u32::try_from(ts.tv_nsec).ok()?;
+    Some(Duration::new(secs, nanos))
+}
+
+/// CPU time consumed by the current thread so far, if the platform and
+/// enabled features can tell.
+#[cfg(not(all(
+    feature = "cpu-time",
+    any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd"
+    )
+)))]
+const fn thread_cpu_time() -> Option<Duration> {
+    None
+}
+
+/// use when the closure carries state that you want back alongside the
+/// result and the time it took to execute.
+///
+/// the state is moved into the closure and handed back by it, so
+/// accumulators survive the `FnOnce` boundary.
+///
+/// ```rust
+/// use voxell_timer::time_fn_with_state;
+///
+/// let (sum, count, _dur) = time_fn_with_state(0_u64, |mut acc| {
+///     let mut count = 0;
+///     for i in 1..=100 {
+///         acc += i;
+///         count += 1;
+///     }
+///     (acc, count)
+/// });
+/// assert_eq!(sum, 5050);
+/// assert_eq!(count, 100);
+/// ```
+#[inline]
+#[must_use = "the state and the measured duration are discarded; use `time_fn` if you don't need the state back"]
+pub fn time_fn_with_state<S, T, F>(state: S, f: F) -> (S, T, Duration)
+where
+    F: FnOnce(S) -> (S, T),
+{
+    let ((state, res), dur) = time_fn(|| f(state));
+    (state, res, dur)
+}
+
+/// use when you want to time the same mutation repeatedly: calls the
+/// closure `iterations` times and returns each call's duration.
+///
+/// unlike [`time_fn`] the closure is `FnMut`, so it can keep mutating
+/// borrowed state between calls.
+///
+/// ```rust
+/// use voxell_timer::time_fn_mut;
+///
+/// let mut data = vec![3, 1, 2];
+/// let durs = time_fn_mut(3, &mut || data.sort_unstable());
+/// assert_eq!(durs.len(), 3);
+/// assert_eq!(data, [1, 2, 3]);
+/// ```
+#[inline]
+#[must_use = "the measured durations are discarded"]
+pub fn time_fn_mut<F>(iterations: usize, f: &mut F) -> Vec<Duration>
+where
+    F: FnMut(),
+{
+    (0..iterations).map(|_| time_fn(&mut *f).1).collect()
+}
+
+/// use for loops that decide themselves when to stop: calls the closure
+/// until it returns `false` and reports `(iterations, total, per_iteration)`.
+///
+/// only calls that returned `true` count as iterations, but the total
+/// includes the final call too. `per_iteration` is zero if the closure
+/// returned `false` straight away.
+///
+/// ```rust
+/// use voxell_timer::time_loop;
+///
+/// let mut remaining = 5;
+/// let (iterations, _total, _per_iteration) = time_loop(|| {
+///     if remaining == 0 {
+///         return false;
+///     }
+///     remaining -= 1;
+///     true
+/// });
+/// assert_eq!(iterations, 5);
+/// ```
+#[inline]
+#[must_use = "the iteration count and durations are discarded"]
+pub fn time_loop<F>(mut f: F) -> (u64, Duration, Duration)
+where
+    F: FnMut() -> bool,
+{
+    let mut iterations = 0_u64;
+    let ((), total) = time_fn(|| {
+        while f() {
+            iterations = iterations.saturating_add(1);
+        }
+    });
+    let per_iteration = total
+        .as_nanos()
+        .checked_div(u128::from(iterations))
+        .and_then(|nanos| u64::try_from(nanos).ok())
+        .map_or(Duration::ZERO, Duration::from_nanos);
+    (iterations, total, per_iteration)
+}
+
+/// use as a quick cache-warmup probe: runs the closure twice and returns
+/// the warm run's result along with the cold and warm durations.
+///
+/// the closure really does run twice, so it must be idempotent for the
+/// warm timing to mean anything. the gap between the two durations is a
+/// rough measure of cache, branch predictor and lazy-init warmup cost.
+///
+/// ```rust
+/// use voxell_timer::time_fn_warm;
+///
+/// let data = (0..10_000).collect::<Vec<u64>>();
+/// let (sum, _cold, _warm) = time_fn_warm(&mut || data.iter().sum::<u64>());
+/// assert_eq!(sum, 49_995_000);
+/// ```
+#[inline]
+#[must_use = "the measured durations are discarded"]
+pub fn time_fn_warm<T, F>(f: &mut F) -> (T, Duration, Duration)
+where
+    F: FnMut() -> T,
+{
+    let (_, cold) = time_fn(&mut *f);
+    let (res, warm) = time_fn(f);
+    (res, cold, warm)
+}
+
+/// use for dirty debugging by printing the time it took to execute
+///
+/// printing is done to `stdout`
+#[inline]
+pub fn time_fn_println<T, F>(label: &str, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let (res, dur) = time_fn(f);
+    println!("{}", timing_line(label, dur));
+    res
+}
+
+/// use for dirty debugging by printing the time it took to execute
+///
+/// printing is done to `stderr`
+#[inline]
+pub fn time_fn_eprintln<T, F>(label: &str, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let (res, dur) = time_fn(f);
+    eprintln!("{}", timing_line(label, dur));
+    res
+}
+
+/// use for dirty debugging across threads by printing the time it took
+/// to execute, prefixed with the current thread's name.
+///
+/// unnamed threads fall back to their `ThreadId`, e.g.
+/// `[worker-2] parse: 3ms` or `[ThreadId(5)] parse: 3ms`.
+///
+/// printing is done to `stdout`
+#[inline]
+pub fn time_fn_println_threaded<T, F>(label: &str, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let (res, dur) = time_fn(f);
+    println!(
+        "{} {}",
+        thread_prefix(&thread::current()),
+        timing_line(label, dur)
+    );
+    res
+}
+
+/// `[name]` for a named thread, `[ThreadId(n)]` otherwise.
+fn thread_prefix(thread: &Thread) -> String {
+    thread.name().map_or_else(
+        || format!("[{:?}]", thread.id()),
+        |name| format!("[{name}]"),
+    )
+}
+
+/// the `label: duration` line the print helpers emit.
+fn timing_line(label: &str, dur: Duration) -> String {
+    format!("{label}: {}", fmt_elapsed(dur))
+}
+
+/// a plain stopwatch for when the start and stop points don't share
+/// a lexical scope.
+///
+/// unlike the closure helpers it is a value, so it can be stored in a
+/// struct field and stopped from a different method.
+///
+/// ```rust
+/// use voxell_timer::Timer;
+///
+/// struct Job {
+///     timer: Option<Timer>,
+/// }
+///
+/// impl Job {
+///     fn begin(&mut self) {
+///         self.timer = Some(Timer::start());
+///     }
+///
+///     fn end(&mut self) -> Option<core::time::Duration> {
+///         self.timer.take().map(Timer::stop)
+///     }
+/// }
+///
+/// let mut job = Job { timer: None };
+/// job.begin();
+/// let dur = job.end();
+/// assert!(dur.is_some());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timer {
+    start: Instant,
+}
+
+impl Timer {
+    /// starts a new stopwatch at the current instant.
+    #[inline]
+    #[must_use]
+    pub fn start() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+
+    /// peeks at the time elapsed so far without stopping.
+    #[inline]
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// stops the stopwatch and returns the total time elapsed.
+    #[inline]
+    #[must_use]
+    pub fn stop(self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// a running count/total/min/max of recorded durations, for when the
+/// individual samples aren't worth keeping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimingAccumulator {
+    count: u64,
+    total: Duration,
+    min: Option<Duration>,
+    max: Duration,
+}
+
+impl TimingAccumulator {
+    /// creates an empty accumulator.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            total: Duration::ZERO,
+            min: None,
+            max: Duration::ZERO,
+        }
+    }
+
+    /// adds one sample.
+    #[inline]
+    pub fn record(&mut self, dur: Duration) {
+        self.count = self.count.saturating_add(1);
+        self.total = self.total.saturating_add(dur);
+        self.min = Some(self.min.map_or(dur, |min| min.min(dur)));
+        self.max = self.max.max(dur);
+    }
+
+    /// how many samples were recorded.
+    #[inline]
+    #[must_use]
+    pub const fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// sum of all samples.
+    #[inline]
+    #[must_use]
+    pub const fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// smallest sample, or zero if nothing was recorded.
+    #[inline]
+    #[must_use]
+    pub fn min(&self) -> Duration {
+        self.min.unwrap_or_default()
+    }
+
+    /// largest sample, or zero if nothing was recorded.
+    #[inline]
+    #[must_use]
+    pub const fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// `total / count`, or zero if nothing was recorded.
+    #[inline]
+    #[must_use]
+    pub fn mean(&self) -> Duration {
+        self.total
+            .as_nanos()
+            .checked_div(u128::from(self.count))
+            .and_then(|nanos| u64::try_from(nanos).ok())
+            .map_or(Duration::ZERO, Duration::from_nanos)
+    }
+}
+
+/// timing adapters for iterators.
+pub trait TimedIteratorExt: Iterator + Sized {
+    /// records how long each element took to produce into `acc`, yielding
+    /// the items unchanged.
+    ///
+    /// the time measured is the call to the underlying `next`, which is
+    /// where the work of any earlier `map`/`filter` stages happens.
+    ///
+    /// ```rust
+    /// use voxell_timer::{TimedIteratorExt, TimingAccumulator};
+    ///
+    /// let mut acc = TimingAccumulator::new();
+    /// let evens = (0..10)
+    ///     .map(|x| x * 2)
+    ///     .time_into(&mut acc)
+    ///     .filter(|x| x % 4 == 0)
+    ///     .collect::<Vec<_>>();
+    ///
+    /// assert_eq!(evens, [0, 4, 8, 12, 16]);
+    /// assert_eq!(acc.count(), 10);
+    /// ```
+    #[inline]
+    fn time_into(self, acc: &mut TimingAccumulator) -> TimeInto<'_, Self> {
+        TimeInto { iter: self, acc }
+    }
+}
+
+impl<It: Iterator> TimedIteratorExt for It {}
+
+/// iterator returned by [`TimedIteratorExt::time_into`].
+#[derive(Debug)]
+pub struct TimeInto<'acc, It> {
+    iter: It,
+    acc: &'acc mut TimingAccumulator,
+}
+
+impl<It: Iterator> Iterator for TimeInto<'_, It> {
+    type Item = It::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<It::Item> {
+        let (item, dur) = time_fn(|| self.iter.next());
+        if item.is_some() {
+            self.acc.record(dur);
+        }
+        item
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// an exponentially-weighted moving average of durations, for a stable
+/// "current typical latency" out of jittery samples.
+///
+/// `alpha` is the weight given to each new sample, between 0 and 1: close
+/// to 1 follows the latest samples closely, close to 0 smooths heavily.
+/// the first sample seeds the average as-is.
+///
+/// ```rust
+/// use core::time::Duration;
+/// use voxell_timer::Ewma;
+///
+/// let mut ewma = Ewma::new(0.5);
+/// ewma.record(Duration::from_millis(10));
+/// ewma.record(Duration::from_millis(20));
+/// assert_eq!(ewma.value(), Duration::from_millis(15));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ewma {
+    alpha: f64,
+    nanos: Option<f64>,
+}
+
+impl Ewma {
+    /// creates an empty average. `alpha` is clamped to `0.0..=1.0`, and a
+    /// NaN `alpha` is treated as `1.0`, i.e. the latest sample wins.
+    #[inline]
+    #[must_use]
+    pub const fn new(alpha: f64) -> Self {
+        Self {
+            alpha: if alpha.is_nan() {
+                1.0
+            } else {
+                alpha.clamp(0.0, 1.0)
+            },
+            nanos: None,
+        }
+    }
+
+    /// folds one sample into the average.
+    #[inline]
+    pub fn record(&mut self, dur: Duration) {
+        let sample = dur.as_secs_f64() * 1e9;
+        self.nanos = Some(match self.nanos {
+            Some(avg) => self.alpha.mul_add(sample - avg, avg),
+            None => sample,
+        });
+    }
+
+    /// the current average, or zero if nothing was recorded.
+    #[inline]
+    #[must_use]
+    pub fn value(&self) -> Duration {
+        self.nanos
+            .and_then(|nanos| Duration::try_from_secs_f64(nanos / 1e9).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// a registry of named probes for timing between points that live in
+/// unrelated functions, e.g. enqueue and dequeue.
+///
+/// `start` records the current instant under a key and a later `stop`
+/// with the same key adds the elapsed time to that key's total.
+///
+/// ```rust
+/// use voxell_timer::Probes;
+///
+/// let mut probes = Probes::new();
+///
+/// fn send(probes: &mut Probes<&'static str>) {
+///     probes.start("db_roundtrip");
+/// }
+///
+/// fn receive(probes: &mut Probes<&'static str>) {
+///     probes.stop("db_roundtrip");
+/// }
+///
+/// send(&mut probes);
+/// receive(&mut probes);
+///
+/// let report = probes.report();
+/// assert_eq!(report.len(), 1);
+/// assert_eq!(report[0].2, 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Probes<K> {
+    open: HashMap<K, Instant>,
+    done: HashMap<K, (Duration, u32)>,
+}
+
+impl<K> Default for Probes<K> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            open: HashMap::new(),
+            done: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash> Probes<K> {
+    /// creates an empty registry.
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// opens the probe `key` at the current instant.
+    ///
+    /// starting a probe that is already open restarts it.
+    #[inline]
+    pub fn start(&mut self, key: K) {
+        self.open.insert(key, Instant::now());
+    }
+
+    /// closes the probe `key` and adds the time since its `start` to the
+    /// key's total.
+    ///
+    /// returns the elapsed time, or `None` if `key` was never started, in
+    /// which case the call is ignored.
+    ///
+    /// like `HashMap::remove`, `key` can be any borrowed form of `K`, so a
+    /// `Probes<String>` can be stopped with a `&str`.
+    #[inline]
+    pub fn stop<Q>(&mut self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key, start) = self.open.remove_entry(key)?;
+        let dur = start.elapsed();
+        let entry = self.done.entry(key).or_default();
+        entry.0 = entry.0.saturating_add(dur);
+        entry.1 = entry.1.saturating_add(1);
+        Some(dur)
+    }
+
+    /// returns `(key, total, stops)` for every probe stopped at least once,
+    /// slowest first. probes that are still open are not included.
+    #[inline]
+    #[must_use]
+    pub fn report(&self) -> Vec<(&K, Duration, u32)> {
+        let mut report = self
+            .done
+            .iter()
+            .map(|(key, &(total, count))| (key, total, count))
+            .collect::<Vec<_>>();
+        report.sort_unstable_by_key(|&(_, total, _)| Reverse(total));
+        report
+    }
+}
+
+/// a flat list of labeled durations that renders as the same table the
+/// benchmark helpers print, for when you don't need anything fancier than
+/// a handful of [`time_fn`] results.
+///
+/// ```rust
+/// use core::time::Duration;
+/// use voxell_timer::{time_fn, FlatReport};
+///
+/// let (_, parse) = time_fn(|| "1 + 2".split('+').count());
+/// let report = [
+///     ("parse".to_owned(), parse),
+///     ("lex".to_owned(), Duration::from_micros(1500)),
+/// ]
+/// .into_iter()
+/// .collect::<FlatReport>();
+///
+/// assert_eq!(report.rows().len(), 2);
+/// assert!(report.to_pretty().contains("parse"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlatReport {
+    rows: Vec<(String, Duration)>,
+}
+
+impl FromIterator<(String, Duration)> for FlatReport {
+    #[inline]
+    fn from_iter<It: IntoIterator<Item = (String, Duration)>>(iter: It) -> Self {
+        Self {
+            rows: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl FlatReport {
+    /// the collected rows, in insertion order.
+    #[inline]
+    #[must_use]
+    pub fn rows(&self) -> &[(String, Duration)] {
+        &self.rows
+    }
+
+    /// renders the rows as a `name | duration` table.
+    #[inline]
+    #[must_use]
+    pub fn to_pretty(&self) -> String {
+        let rows = self
+            .rows
+            .iter()
+            .map(|&(ref label, dur)| vec![label.clone(), fmt_elapsed(dur)])
+            .collect::<Vec<_>>();
+        render_table(&["name", "duration"], &rows)
+    }
+}
+
+/// summary of the per-run durations collected by [`bench_fn`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BenchStats {
+    /// how many times the closure was run.
+    pub runs: u32,
+    /// sum of all run durations.
+    pub total: Duration,
+    /// fastest run.
+    pub min: Duration,
+    /// slowest run.
+    pub max: Duration,
+    /// `total / runs`.
+    pub mean: Duration,
+    /// middle run, or the average of the two middle runs.
+    pub median: Duration,
+}
+
+impl BenchStats {
+    /// whether the median is too close to the clock resolution to be
+    /// trusted, i.e. under `factor` times `resolution`.
+    ///
+    /// [`RESOLUTION_WARN_FACTOR`] is a reasonable default for `factor`.
+    ///
+    /// ```rust
+    /// use voxell_timer::{bench_fn, clock_resolution_estimate, RESOLUTION_WARN_FACTOR};
+    ///
+    /// let stats = bench_fn(100, || 1 + 1);
+    /// if stats.is_near_resolution(clock_resolution_estimate(), RESOLUTION_WARN_FACTOR) {
+    ///     eprintln!("too fast to measure reliably, batch more work per run");
+    /// }
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn is_near_resolution(&self, resolution: Duration, factor: u32) -> bool {
+        self.median < resolution.saturating_mul(factor)
+    }
+
+    /// computes the stats for a set of per-run durations.
+    ///
+    /// the samples are sorted in place. an empty slice gives all zeroes.
+    #[inline]
+    #[must_use]
+    pub fn from_samples(samples: &mut [Duration]) -> Self {
+        samples.sort_unstable();
+        let (Some(&min), Some(&max)) = (samples.first(), samples.last()) else {
+            return Self::default();
+        };
+        let runs = u32::try_from(samples.len()).unwrap_or(u32::MAX);
+        let total = samples
+            .iter()
+            .fold(Duration::ZERO, |acc, &d| acc.saturating_add(d));
+        let mean = total.checked_div(runs).unwrap_or_default();
+        let mid = samples.len() / 2;
+        let median = if samples.len().is_multiple_of(2) {
+            let lo = samples
+                .get(mid.saturating_sub(1))
+                .copied()
+                .unwrap_or_default();
+            let hi = samples.get(mid).copied().unwrap_or_default();
+            lo.saturating_add(hi).checked_div(2).unwrap_or_default()
+        } else {
+            samples.get(mid).copied().unwrap_or_default()
+        };
+        Self {
+            runs,
+            total,
+            min,
+            max,
+            mean,
+            median,
+        }
+    }
+
+    /// merges two stat sets, e.g. from benchmark shards run in parallel.
+    ///
+    /// `runs`, `total`, `min` and `max` combine exactly, and `mean` is
+    /// recomputed as `total / runs`, which equals the run-weighted mean of the
+    /// two means. `median` can't be recovered without the raw samples, so it
+    /// is approximated by the run-weighted mean of the two medians.
+    ///
+    /// ```rust
+    /// use voxell_timer::bench_fn;
+    ///
+    /// let a = bench_fn(10, || 1 + 1);
+    /// let b = bench_fn(30, || 1 + 1);
+    /// let all = a.combine(&b);
+    /// assert_eq!(all.runs, 40);
+    /// assert_eq!(all.min, a.min.min(b.min));
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn combine(&self, other: &Self) -> Self {
+        if other.runs == 0 {
+            return *self;
+        }
+        if self.runs == 0 {
+            return *other;
+        }
+        let runs = self.runs.saturating_add(other.runs);
+        let total = self.total.saturating_add(other.total);
+        let median = self
+            .median
+            .saturating_mul(self.runs)
+            .saturating_add(other.median.saturating_mul(other.runs))
+            .checked_div(runs)
+            .unwrap_or_default();
+        Self {
+            runs,
+            total,
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            mean: total.checked_div(runs).unwrap_or_default(),
+            median,
+        }
+    }
+}
+
+/// use for quick micro-benchmarks: runs the closure `runs` times and
+/// summarizes the per-run durations.
+///
+/// the closure's return value is passed through [`black_box`] so it can't
+/// be optimized away, but inputs captured by the closure still should be.
+#[inline]
+#[must_use = "the benchmark stats are discarded"]
+pub fn bench_fn<T, F>(runs: u32, mut f: F) -> BenchStats
+where
+    F: FnMut() -> T,
+{
+    let mut samples = (0..runs)
+        .map(|_| time_fn(|| black_box(f())).1)
+        .collect::<Vec<_>>();
+    BenchStats::from_samples(&mut samples)
+}
+
+/// streams [`BenchStats`] to a CSV writer one row at a time, for parameter
+/// sweeps too long to keep every result in memory.
+///
+/// durations are written as whole nanoseconds.
+///
+/// ```rust
+/// use voxell_timer::{bench_fn, BenchCsvWriter};
+///
+/// let mut csv = BenchCsvWriter::new(Vec::new());
+/// csv.write_header()?;
+/// for n in [10, 100] {
+///     let stats = bench_fn(5, || (0..n).sum::<u64>());
+///     csv.write_run(&format!("sum {n}"), &stats)?;
+/// }
+///
+/// let out = String::from_utf8(csv.into_inner()).unwrap();
+/// assert_eq!(out.lines().count(), 3);
+/// assert!(out.starts_with("label,runs,total_ns,min_ns,max_ns,mean_ns,median_ns\n"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct BenchCsvWriter<W> {
+    writer: W,
+}
+
+impl<W: io::Write> BenchCsvWriter<W> {
+    /// wraps a writer. nothing is written until `write_header`/`write_run`.
+    #[inline]
+    #[must_use]
+    pub const fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// writes the column names.
+    ///
+    /// # Errors
+    ///
+    /// returns any error from the underlying writer.
+    #[inline]
+    pub fn write_header(&mut self) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            "label,runs,total_ns,min_ns,max_ns,mean_ns,median_ns"
+        )
+    }
+
+    /// writes one row for `stats`, quoting `label` if it needs it.
+    ///
+    /// # Errors
+    ///
+    /// returns any error from the underlying writer.
+    #[inline]
+    pub fn write_run(&mut self, label: &str, stats: &BenchStats) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            "{},{},{},{},{},{},{}",
+            csv_field(label),
+            stats.runs,
+            stats.total.as_nanos(),
+            stats.min.as_nanos(),
+            stats.max.as_nanos(),
+            stats.mean.as_nanos(),
+            stats.median.as_nanos(),
+        )
+    }
+
+    /// unwraps the underlying writer.
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// quotes a CSV field if it contains a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// use for quick A/B testing of two implementations.
+///
+/// benchmarks both closures `runs` times each and prints their mean and
+/// median along with how much faster or slower `b` is than `a`.
+///
+/// printing is done to `stdout`
+///
+/// ```rust
+/// use voxell_timer::bench_compare;
+///
+/// let data = (0..1000).rev().collect::<Vec<u32>>();
+/// let (a, b) = bench_compare(
+///     "sort",
+///     || {
+///         let mut v = data.clone();
+///         v.sort();
+///         v
+///     },
+///     "sort_unstable",
+///     || {
+///         let mut v = data.clone();
+///         v.sort_unstable();
+///         v
+///     },
+///     10,
+/// );
+/// assert_eq!(a.runs, 10);
+/// assert_eq!(b.runs, 10);
+/// ```
+#[inline]
+pub fn bench_compare<A, B, FA, FB>(
+    name_a: &str,
+    f_a: FA,
+    name_b: &str,
+    f_b: FB,
+    runs: u32,
+) -> (BenchStats, BenchStats)
+where
+    FA: FnMut() -> A,
+    FB: FnMut() -> B,
+{
+    bench_compare_inner(name_a, f_a, name_b, f_b, runs, None)
+}
+
+/// like [`bench_compare`], but also prints a warning for each side whose
+/// median is under `warn_factor` times [`clock_resolution_estimate`], since
+/// such timings are mostly noise.
+///
+/// [`RESOLUTION_WARN_FACTOR`] is a reasonable default for `warn_factor`.
+///
+/// ```rust
+/// use voxell_timer::{bench_compare_with_warning, RESOLUTION_WARN_FACTOR};
+///
+/// let (a, b) = bench_compare_with_warning(
+///     "add",
+///     || 1 + 1,
+///     "mul",
+///     || 2 * 1,
+///     10,
+///     RESOLUTION_WARN_FACTOR,
+/// );
+/// assert_eq!(a.runs, b.runs);
+/// ```
+#[inline]
+pub fn bench_compare_with_warning<A, B, FA, FB>(
+    name_a: &str,
+    f_a: FA,
+    name_b: &str,
+    f_b: FB,
+    runs: u32,
+    warn_factor: u32,
+) -> (BenchStats, BenchStats)
+where
+    FA: FnMut() -> A,
+    FB: FnMut() -> B,
+{
+    bench_compare_inner(name_a, f_a, name_b, f_b, runs, Some(warn_factor))
+}
+
+/// shared body of [`bench_compare`] and [`bench_compare_with_warning`].
+fn bench_compare_inner<A, B, FA, FB>(
+    name_a: &str,
+    f_a: FA,
+    name_b: &str,
+    f_b: FB,
+    runs: u32,
+    warn_factor: Option<u32>,
+) -> (BenchStats, BenchStats)
+where
+    FA: FnMut() -> A,
+    FB: FnMut() -> B,
+{
+    let a = bench_fn(runs, f_a);
+    let b = bench_fn(runs, f_b);
+
+    let rows = [(name_a, &a), (name_b, &b)].map(|(name, stats)| {
+        vec![
+            name.to_owned(),
+            fmt_elapsed(stats.mean),
+            fmt_elapsed(stats.median),
+        ]
+    });
+    print!("{}", render_table(&["name", "mean", "median"], &rows));
+    println!("{}", speedup_line(name_a, &a, name_b, &b));
+
+    if let Some(factor) = warn_factor {
+        let resolution = clock_resolution_estimate();
+        for (name, stats) in [(name_a, &a), (name_b, &b)] {
+            if stats.is_near_resolution(resolution, factor) {
+                println!(
+                    "warning: {name} median is within {factor}x of the clock \
+                     resolution ({resolution:?}), its timings are mostly noise"
+                );
+            }
+        }
+    }
+
+    (a, b)
+}
+
+/// the default multiple of the clock resolution a measurement needs before
+/// it's considered meaningful.
+pub const RESOLUTION_WARN_FACTOR: u32 = 10;
+
+/// empirically estimates the resolution of [`Instant`]: the smallest
+/// nonzero difference observed between successive `Instant::now()` calls.
+///
+/// on most platforms this is tens of nanoseconds, dominated by the cost of
+/// the call itself. some platforms are much coarser, which makes anything
+/// measured below a few multiples of it meaningless.
+///
+/// the estimate is measured on the first call and cached for the rest of
+/// the process.
+///
+/// ```rust
+/// use voxell_timer::clock_resolution_estimate;
+///
+/// assert!(!clock_resolution_estimate().is_zero());
+/// ```
+#[inline]
+#[must_use]
+pub fn clock_resolution_estimate() -> Duration {
+    static RESOLUTION: OnceLock<Duration> = OnceLock::new();
+    *RESOLUTION.get_or_init(|| {
+        (0..100)
+            .map(|_| {
+                let start = Instant::now();
+                loop {
+                    let delta = start.elapsed();
+                    if !delta.is_zero() {
+                        break delta;
+                    }
+                }
+            })
+            .min()
+            .unwrap_or_default()
+    })
+}
+
+/// renders rows as a plain-text table: the first column left-aligned, the
+/// rest right-aligned, each padded to its widest cell.
+fn render_table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths = header.iter().map(|h| h.chars().count()).collect::<Vec<_>>();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header = header.iter().map(|&h| h.to_owned()).collect::<Vec<_>>();
+    let mut out = String::new();
+    for row in iter::once(&header).chain(rows) {
+        for (i, (cell, &width)) in row.iter().zip(&widths).enumerate() {
+            let _ = if i == 0 {
+                write!(out, "{cell:<width$}")
+            } else {
+                write!(out, "  {cell:>width$}")
+            };
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn speedup_line(name_a: &str, a: &BenchStats, name_b: &str, b: &BenchStats) -> String {
+    let mean_a = a.mean.as_secs_f64();
+    let mean_b = b.mean.as_secs_f64();
+    if mean_a <= 0.0 || mean_b <= 0.0 {
+        return format!("{name_b} vs {name_a}: too fast to compare");
+    }
+    if mean_b <= mean_a {
+        format!("{name_b} is {:.2}x faster than {name_a}", mean_a / mean_b)
+    } else {
+        format!("{name_b} is {:.2}x slower than {name_a}", mean_b / mean_a)
+    }
+}
+
+/// a scope whose current time exceeded its baseline, as reported by
+/// [`compare_to_baseline`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Regression<I> {
+    /// the scope's identifier.
+    pub ident: I,
+    /// the scope's time in the baseline run.
+    pub baseline: Duration,
+    /// the scope's time in the current run.
+    pub current: Duration,
+}
+
+/// use as a performance gate in CI: compares per-scope `(ident, time, count)`
+/// results against a recorded baseline and returns every scope that got
+/// slower by more than `tolerance`.
+///
+/// `tolerance` is fractional, so `0.1` allows up to 10% slowdown. scopes
+/// missing from the baseline are treated as new and never reported; scopes
+/// missing from the current run are ignored. the counts are not compared.
+///
+/// ```rust
+/// use core::time::Duration;
+/// use voxell_timer::compare_to_baseline;
+///
+/// let ms = Duration::from_millis;
+/// let baseline = [("parse", ms(10), 1), ("lex", ms(5), 1)];
+/// let current = [("parse", ms(10), 1), ("lex", ms(8), 1), ("new", ms(1), 1)];
+///
+/// let regressions = compare_to_baseline(&current, &baseline, 0.1);
+/// assert_eq!(regressions.len(), 1);
+/// assert_eq!(regressions[0].ident, "lex");
+/// ```
+#[inline]
+#[must_use]
+pub fn compare_to_baseline<I>(
+    current: &[(I, Duration, u32)],
+    baseline: &[(I, Duration, u32)],
+    tolerance: f64,
+) -> Vec<Regression<I>>
+where
+    I: PartialEq + Clone,
+{
+    compare_to_baseline_with_significance(current, baseline, tolerance, Duration::ZERO)
+}
+
+/// like [`compare_to_baseline`], but a scope only counts as a regression if
+/// it also got slower by more than `significance` in absolute terms.
+///
+/// use it to keep a CI gate from flaking on scopes so fast that a few
+/// microseconds of noise blow past the relative tolerance.
+///
+/// ```rust
+/// use core::time::Duration;
+/// use voxell_timer::compare_to_baseline_with_significance;
+///
+/// let us = Duration::from_micros;
+/// let baseline = [("tiny", us(2), 1), ("parse", us(1000), 1)];
+/// let current = [("tiny", us(4), 1), ("parse", us(1500), 1)];
+///
+/// let regressions = compare_to_baseline_with_significance(&current, &baseline, 0.1, us(100));
+/// assert_eq!(regressions.len(), 1);
+/// assert_eq!(regressions[0].ident, "parse");
+/// ```
+#[inline]
+#[must_use]
+pub fn compare_to_baseline_with_significance<I>(
+    current: &[(I, Duration, u32)],
+    baseline: &[(I, Duration, u32)],
+    tolerance: f64,
+    significance: Duration,
+) -> Vec<Regression<I>>
+where
+    I: PartialEq + Clone,
+{
+    current
+        .iter()
+        .filter_map(|&(ref ident, current, _)| {
+            let &(_, baseline, _) = baseline.iter().find(|entry| entry.0 == *ident)?;
+            let limit = baseline.as_secs_f64() * (1.0 + tolerance);
+            let significant = current.saturating_sub(baseline) > significance;
+            (significant && current.as_secs_f64() > limit).then(|| Regression {
+                ident: ident.clone(),
+                baseline,
+                current,
+            })
+        })
+        .collect()
+}
+
+/// allocation activity on the current thread, as counted by
+/// [`CountingAlloc`].
+#[cfg(feature = "alloc-stats")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocStats {
+    /// number of allocations, including reallocations.
+    pub allocations: u64,
+    /// number of deallocations.
+    pub deallocations: u64,
+    /// bytes requested by allocations and reallocations.
+    pub bytes_allocated: u64,
+}
+
+#[cfg(feature = "alloc-stats")]
+thread_local! {
+    static ALLOC_STATS: Cell<AllocStats> = const {
+        Cell::new(AllocStats {
+            allocations: 0,
+            deallocations: 0,
+            bytes_allocated: 0,
+        })
+    };
+}
+
+#[cfg(feature = "alloc-stats")]
+fn update_alloc_stats<F: FnOnce(&mut AllocStats)>(f: F) {
+    // `try_with` because the allocator is still called while thread locals
+    // are being torn down.
+    let _ = ALLOC_STATS.try_with(|cell| {
+        let mut stats = cell.get();
+        f(&mut stats);
+        cell.set(stats);
+    });
+}
+
+#[cfg(feature = "alloc-stats")]
+fn record_alloc(size: usize) {
+    update_alloc_stats(|stats| {
+        stats.allocations = stats.allocations.saturating_add(1);
+        stats.bytes_allocated = stats
+            .bytes_allocated
+            .saturating_add(u64::try_from(size).unwrap_or(u64::MAX));
+    });
+}
+
+/// a global allocator wrapper that counts allocations per thread, for use
+/// with [`time_and_alloc`].
+///
+/// it has to be installed as the program's global allocator, otherwise
+/// [`time_and_alloc`] reports all zeroes:
+///
+/// ```rust
+/// use voxell_timer::CountingAlloc;
+///
+/// #[global_allocator]
+/// static GLOBAL: CountingAlloc = CountingAlloc::system();
+/// ```
+///
+/// counters are thread-local, so only allocations made on the thread that
+/// calls [`time_and_alloc`] are attributed to it.
+#[cfg(feature = "alloc-stats")]
+#[derive(Debug, Default)]
+pub struct CountingAlloc<A = System>(pub A);
+
+#[cfg(feature = "alloc-stats")]
+impl CountingAlloc {
+    /// wraps the system allocator.
+    #[inline]
+    #[must_use]
+    pub const fn system() -> Self {
+        Self(System)
+    }
+}
+
+// SAFETY: every method forwards to the wrapped allocator with the caller's
+// arguments unchanged; the bookkeeping never allocates.
+#[cfg(feature = "alloc-stats")]
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAlloc<A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record_alloc(layout.size());
+        // SAFETY: the caller upholds `GlobalAlloc::alloc`'s contract.
+        unsafe { self.0.alloc(layout) }
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record_alloc(layout.size());
+        // SAFETY: the caller upholds `GlobalAlloc::alloc_zeroed`'s contract.
+        unsafe { self.0.alloc_zeroed(layout) }
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        update_alloc_stats(|stats| {
+            stats.deallocations = stats.deallocations.saturating_add(1);
+        });
+        // SAFETY: the caller upholds `GlobalAlloc::dealloc`'s contract.
+        unsafe { self.0.dealloc(ptr, layout) }
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record_alloc(new_size);
+        // SAFETY: the caller upholds `GlobalAlloc::realloc`'s contract.
+        unsafe { self.0.realloc(ptr, layout, new_size) }
+    }
+}
+
+/// like [`time_fn`], but also reports how much the closure allocated on the
+/// current thread.
+///
+/// requires [`CountingAlloc`] to be installed as the global allocator.
+#[cfg(feature = "alloc-stats")]
+#[inline]
+#[must_use = "the measured duration and allocation stats are discarded"]
+pub fn time_and_alloc<T, F>(f: F) -> (T, Duration, AllocStats)
+where
+    F: FnOnce() -> T,
+{
+    let before = ALLOC_STATS.with(Cell::get);
+    let (res, dur) = time_fn(f);
+    let after = ALLOC_STATS.with(Cell::get);
+    let stats = AllocStats {
+        allocations: after.allocations.saturating_sub(before.allocations),
+        deallocations: after.deallocations.saturating_sub(before.deallocations),
+        bytes_allocated: after.bytes_allocated.saturating_sub(before.bytes_allocated),
+    };
+    (res, dur, stats)
+}
+
+/// times a future by how long it spends inside `poll`, not by how long
+/// it exists.
+///
+/// time the task spends parked between polls, waiting on I/O or on the
+/// executor, is not counted, so this measures active poll time rather
+/// than wall time.
+///
+/// ```rust
+/// use core::future::Future;
+/// use core::pin::pin;
+/// use core::task::{Context, Poll, Waker};
+/// use voxell_timer::time_poll;
+///
+/// let mut fut = pin!(time_poll(async { 5 + 3 }));
+/// let mut cx = Context::from_waker(Waker::noop());
+/// let Poll::Ready((res, _busy)) = fut.as_mut().poll(&mut cx) else {
+///     unreachable!()
+/// };
+/// assert_eq!(res, 8);
+/// ```
+#[cfg(feature = "async")]
+#[inline]
+pub const fn time_poll<F: Future>(fut: F) -> PollTimed<F> {
+    PollTimed {
+        inner: fut,
+        busy: Duration::ZERO,
+    }
+}
+
+/// future returned by [`time_poll`], resolving to the inner future's
+/// output and its accumulated poll time.
+#[cfg(feature = "async")]
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct PollTimed<F> {
+    inner: F,
+    busy: Duration,
+}
+
+#[cfg(feature = "async")]
+impl<F: Future> Future for PollTimed<F> {
+    type Output = (F::Output, Duration);
+
+    #[inline]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `inner` is structurally pinned: it is only ever accessed
+        // through the `Pin` below and never moved out of, and there is no
+        // `Drop` impl. `busy` is not pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        // SAFETY: see above.
+        let inner = unsafe { Pin::new_unchecked(&mut this.inner) };
+        let (poll, dur) = time_fn(|| inner.poll(cx));
+        this.busy = this.busy.saturating_add(dur);
+        poll.map(|res| (res, this.busy))
+    }
+}
+
+/// items per second processed in `dur`.
+///
+/// returns `None` for a zero duration instead of `inf`, since "too fast to
+/// measure" says nothing about the actual rate.
+///
+/// ```rust
+/// use core::time::Duration;
+/// use voxell_timer::throughput;
+///
+/// assert_eq!(throughput(500, Duration::from_millis(250)), Some(2000.0));
+/// assert_eq!(throughput(500, Duration::ZERO), None);
+/// ```
+#[inline]
+#[must_use]
+#[expect(
+    clippy::cast_precision_loss,
+    reason = "throughput is an estimate; counts beyond 2^53 lose precision"
+)]
+pub fn throughput(count: u64, dur: Duration) -> Option<f64> {
+    if dur.is_zero() {
+        return None;
+    }
+    Some(count as f64 / dur.as_secs_f64())
+}
+
+/// estimates how many cycles a clock running at `hz` completes in `dur`.
+///
+/// this assumes a fixed frequency, which turbo boost and power saving make
+/// only approximately true. saturates at `u64::MAX` instead of overflowing.
+///
+/// ```rust
+/// use core::time::Duration;
+/// use voxell_timer::duration_to_cycles;
+///
+/// assert_eq!(duration_to_cycles(Duration::from_micros(1), 3_000_000_000), 3_000);
+/// assert_eq!(duration_to_cycles(Duration::MAX, u64::MAX), u64::MAX);
+/// ```
+#[inline]
+#[must_use]
+pub fn duration_to_cycles(dur: Duration, hz: u64) -> u64 {
+    let cycles = dur.as_nanos().saturating_mul(u128::from(hz)) / 1_000_000_000;
+    u64::try_from(cycles).unwrap_or(u64::MAX)
+}
+
+/// converts a cycle count at a clock running at `hz` into a duration, the
+/// inverse of [`duration_to_cycles`].
+///
+/// a zero frequency gives a zero duration. saturates at [`Duration::MAX`].
+///
+/// ```rust
+/// use core::time::Duration;
+/// use voxell_timer::cycles_to_duration;
+///
+/// assert_eq!(cycles_to_duration(3_000, 3_000_000_000), Duration::from_micros(1));
+/// ```
+#[inline]
+#[must_use]
+pub fn cycles_to_duration(cycles: u64, hz: u64) -> Duration {
+    let Some(nanos) = (u128::from(cycles) * 1_000_000_000).checked_div(u128::from(hz)) else {
+        return Duration::ZERO;
+    };
+    let secs = nanos / 1_000_000_000;
+    let subsec = nanos % 1_000_000_000;
+    match (u64::try_from(secs), u32::try_from(subsec)) {
+        (Ok(secs), Ok(subsec)) => Duration::new(secs, subsec),
+        _ => Duration::MAX,
+    }
+}
+
+/// like [`time_fn`], but counts CPU timestamp-counter ticks with
+/// fenced `rdtsc` reads instead of reading [`Instant`], for when even
+/// `Instant::now()` is too heavy.
+///
+/// the count is raw ticks, not time. convert with [`cycles_to_duration`]
+/// and a frequency from [`tsc_hz_estimate`] or the CPU's spec sheet. the
+/// reading is only trustworthy on CPUs with an invariant TSC, and may be
+/// skewed if the thread migrates between cores mid-measurement; pin the
+/// thread for serious work.
+#[cfg(all(feature = "tsc", target_arch = "x86_64"))]
+#[inline]
+#[must_use = "the measured cycle count is discarded"]
+pub fn time_fn_tsc<T, F>(f: F) -> (T, u64)
+where
+    F: FnOnce() -> T,
+{
+    let start = fenced_rdtsc();
+    let res = f();
+    let end = fenced_rdtsc();
+    (res, end.wrapping_sub(start))
+}
+
+/// reads the timestamp counter once all earlier instructions have finished.
+#[cfg(all(feature = "tsc", target_arch = "x86_64"))]
+#[inline]
+fn fenced_rdtsc() -> u64 {
+    // SAFETY: `lfence` is part of SSE2, which every x86_64 CPU has.
+    unsafe { _mm_lfence() };
+    // SAFETY: `rdtsc` is available on every x86_64 CPU.
+    unsafe { _rdtsc() }
+}
+
+/// estimates the timestamp counter's frequency in Hz by counting ticks
+/// across a busy-wait of `sample` wall time.
+///
+/// longer samples give better estimates; 10-100ms is usually plenty.
+#[cfg(all(feature = "tsc", target_arch = "x86_64"))]
+#[inline]
+#[must_use]
+pub fn tsc_hz_estimate(sample: Duration) -> u64 {
+    let ((), ticks) = time_fn_tsc(|| {
+        let start = Instant::now();
+        while start.elapsed() < sample {}
+    });
+    let hz = (u128::from(ticks) * 1_000_000_000)
+        .checked_div(sample.as_nanos())
+        .unwrap_or_default();
+    u64::try_from(hz).unwrap_or(u64::MAX)
+}
+
+/// a unit [`FormatConfig`] can render a duration in, smallest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TimeUnit {
+    /// `ns`
+    Nanos,
+    /// `µs`
+    Micros,
+    /// `ms`
+    Millis,
+    /// `s`
+    Secs,
+    /// `m`
+    Mins,
+    /// `h`
+    Hours,
+}
+
+impl TimeUnit {
+    /// every unit, largest first.
+    const DESCENDING: [Self; 6] = [
+        Self::Hours,
+        Self::Mins,
+        Self::Secs,
+        Self::Millis,
+        Self::Micros,
+        Self::Nanos,
+    ];
+
+    /// the unit's length in nanoseconds.
+    const fn nanos(self) -> u128 {
+        match self {
+            Self::Nanos => 1,
+            Self::Micros => 1_000,
+            Self::Millis => 1_000_000,
+            Self::Secs => 1_000_000_000,
+            Self::Mins => 60_000_000_000,
+            Self::Hours => 3_600_000_000_000,
+        }
+    }
+
+    /// the unit's suffix, e.g. `ms`.
+    #[inline]
+    #[must_use]
+    pub const fn suffix(self) -> &'static str {
+        match self {
+            Self::Nanos => "ns",
+            Self::Micros => "µs",
+            Self::Millis => "ms",
+            Self::Secs => "s",
+            Self::Mins => "m",
+            Self::Hours => "h",
+        }
+    }
+}
+
+/// how [`FormatConfig::format`] rounds a value to its significant figures.
+///
+/// defaults to [`RoundingMode::HalfUp`], which is what people expect when
+/// reading a single number.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum RoundingMode {
+    /// drop the extra digits, giving a stable lower bound: `1.239ms` is
+    /// `1.23ms`.
+    Truncate,
+    /// round ties away from zero: `1.235ms` is `1.24ms`.
+    #[default]
+    HalfUp,
+    /// round ties to the even digit, which avoids drifting upwards when
+    /// many rounded values are aggregated: `1.225ms` is `1.22ms`.
+    HalfEven,
+}
+
+/// controls how [`FormatConfig::format`] picks units and rounds.
+///
+/// the largest unit between `min_unit` and `max_unit` that keeps the value
+/// at or above 1 is used, falling back to `min_unit` for durations shorter
+/// than one `min_unit`. the value in that unit is rounded half-up to
+/// `significant_figures`, moving up a unit if rounding carries over (`59.96s`
+/// becomes `1m`), and trailing zeros are trimmed. if `min_unit` is larger
+/// than `max_unit`, `min_unit` wins.
+///
+/// the default (nanoseconds to hours, 3 significant figures) is what
+/// [`fmt_duration`] uses.
+///
+/// ```rust
+/// use core::time::Duration;
+/// use voxell_timer::{FormatConfig, TimeUnit};
+///
+/// let house_style = FormatConfig {
+///     min_unit: TimeUnit::Millis,
+///     max_unit: TimeUnit::Secs,
+///     significant_figures: 4,
+///     ..FormatConfig::default()
+/// };
+/// assert_eq!(house_style.format(Duration::from_micros(250)), "0.25ms");
+/// assert_eq!(house_style.format(Duration::from_millis(12_345)), "12.35s");
+/// assert_eq!(house_style.format(Duration::from_secs(7_200)), "7200s");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FormatConfig {
+    /// the smallest unit to render in.
+    pub min_unit: TimeUnit,
+    /// the largest unit to render in.
+    pub max_unit: TimeUnit,
+    /// how many significant figures to round to, clamped to `1..=17`.
+    pub significant_figures: u32,
+    /// how to round to `significant_figures`.
+    pub rounding: RoundingMode,
+}
+
+impl Default for FormatConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            min_unit: TimeUnit::Nanos,
+            max_unit: TimeUnit::Hours,
+            significant_figures: 3,
+            rounding: RoundingMode::HalfUp,
+        }
+    }
+}
+
+/// the most significant figures [`FormatConfig::format`] will show.
+const MAX_SIGNIFICANT_FIGURES: u32 = 17;
+
+impl FormatConfig {
+    /// formats `dur` according to this config.
+    #[inline]
+    #[must_use]
+    pub fn format(&self, dur: Duration) -> String {
+        let sig = self.significant_figures.clamp(1, MAX_SIGNIFICANT_FIGURES);
+        let max_unit = self.max_unit.max(self.min_unit);
+        let mut nanos = dur.as_nanos();
+        loop {
+            let unit = self.unit_for(nanos, max_unit);
+            let scale = unit.nanos();
+            let (decimals, shift) = precision(nanos, scale, sig);
+            let decimal_factor = 10_u128.saturating_pow(decimals);
+            let shift_factor = 10_u128.saturating_pow(shift);
+            let rounded = round_div(
+                nanos.saturating_mul(decimal_factor),
+                scale.saturating_mul(shift_factor),
+                self.rounding,
+            );
+
+            // rounding can carry into the next unit up, e.g. 59.96s to 60s
+            let rounded_nanos = rounded
+                .saturating_mul(shift_factor)
+                .saturating_mul(scale)
+                .checked_div(decimal_factor)
+                .unwrap_or_default();
+            if self.unit_for(rounded_nanos, max_unit) != unit {
+                nanos = rounded_nanos;
+                continue;
+            }
+
+            let mut out = rounded
+                .checked_div(decimal_factor)
+                .unwrap_or_default()
+                .saturating_mul(shift_factor)
+                .to_string();
+            let frac = rounded.checked_rem(decimal_factor).unwrap_or_default();
+            let width = usize::try_from(decimals).unwrap_or(0);
+            let frac = format!("{frac:0width$}");
+            let frac = frac.trim_end_matches('0');
+            if !frac.is_empty() {
+                out.push('.');
+                out.push_str(frac);
+            }
+            out.push_str(unit.suffix());
+            return out;
+        }
+    }
+
+    /// the largest allowed unit that `nanos` is at least one of, falling
+    /// back to `min_unit`.
+    fn unit_for(self, nanos: u128, max_unit: TimeUnit) -> TimeUnit {
+        TimeUnit::DESCENDING
+            .into_iter()
+            .filter(|&unit| unit <= max_unit && unit >= self.min_unit)
+            .find(|&unit| nanos >= unit.nanos())
+            .unwrap_or(self.min_unit)
+    }
+}
+
+/// how to scale `nanos / scale` so that rounding it to an integer keeps
+/// `sig` significant figures: `(decimals, shift)` multiplies the value by
+/// `10^decimals` and divides it by `10^shift`. at most one is nonzero.
+fn precision(nanos: u128, scale: u128, sig: u32) -> (u32, u32) {
+    let Some(whole) = nanos.checked_div(scale) else {
+        return (0, 0);
+    };
+    let keep = sig.saturating_sub(1);
+    if let Some(log) = whole.checked_ilog10() {
+        (keep.saturating_sub(log), log.saturating_sub(keep))
+    } else {
+        // below one unit, only reachable through `min_unit`: skip the
+        // leading zeros after the point before counting figures
+        let Some(ratio) = scale.checked_div(nanos) else {
+            return (0, 0);
+        };
+        let log = ratio.ilog10();
+        let exact = scale.checked_rem(nanos) == Some(0) && 10_u128.checked_pow(log) == Some(ratio);
+        let leading = if exact { log } else { log.saturating_add(1) };
+        (keep.saturating_add(leading), 0)
+    }
+}
+
+/// formats a duration compactly in the largest unit that keeps it at or
+/// above 1, rounded to 3 significant figures: `12ns`, `1.23µs`, `45.6ms`,
+/// `2.5s`, `1.03h`.
 ///
-/// printing is done to `stdout`
+/// this is [`FormatConfig::default`]; build a [`FormatConfig`] to change
+/// the unit bounds or precision.
+///
+/// ```rust
+/// use core::time::Duration;
+/// use voxell_timer::fmt_duration;
+///
+/// assert_eq!(fmt_duration(Duration::from_nanos(1234)), "1.23µs");
+/// assert_eq!(fmt_duration(Duration::from_micros(999_600)), "1s");
+/// assert_eq!(fmt_duration(Duration::ZERO), "0ns");
+/// ```
 #[inline]
-pub fn time_fn_println<T, F>(label: &str, f: F) -> T
-where
-    F: FnOnce() -> T,
-{
-    let (res, dur) = time_fn(f);
-    println!("{}: {}ms", label, dur.as_millis());
-    res
+#[must_use]
+pub fn fmt_duration(dur: Duration) -> String {
+    FormatConfig::default().format(dur)
 }
 
-/// use for dirty debugging by printing the time it took to execute
+/// divides `num` by `den`, rounding according to `mode`.
+const fn round_div(num: u128, den: u128, mode: RoundingMode) -> u128 {
+    let (Some(quot), Some(rem)) = (num.checked_div(den), num.checked_rem(den)) else {
+        return 0;
+    };
+    let above = den.saturating_sub(rem);
+    let round_up = match mode {
+        RoundingMode::Truncate => false,
+        RoundingMode::HalfUp => rem >= above,
+        RoundingMode::HalfEven => rem > above || (rem == above && quot % 2 == 1),
+    };
+    if round_up {
+        quot.saturating_add(1)
+    } else {
+        quot
+    }
+}
+
+/// formats a duration the way the print helpers, macros and tables do.
 ///
-/// printing is done to `stderr`
+/// by default this is [`fmt_duration`], e.g. `1.5ms`. with the `humantime`
+/// feature enabled it defers to `humantime::format_duration` instead.
+///
+/// ```rust
+/// use core::time::Duration;
+/// use voxell_timer::fmt_elapsed;
+///
+/// let dur = Duration::from_secs(3723);
+/// #[cfg(not(feature = "humantime"))]
+/// assert_eq!(fmt_elapsed(dur), "1.03h");
+/// #[cfg(feature = "humantime")]
+/// assert_eq!(fmt_elapsed(dur), "1h 2m 3s");
+/// ```
 #[inline]
-pub fn time_fn_eprintln<T, F>(label: &str, f: F) -> T
-where
-    F: FnOnce() -> T,
-{
-    let (res, dur) = time_fn(f);
-    eprintln!("{}: {}ms", label, dur.as_millis());
-    res
+#[must_use]
+pub fn fmt_elapsed(dur: Duration) -> String {
+    #[cfg(feature = "humantime")]
+    {
+        humantime::format_duration(dur).to_string()
+    }
+    #[cfg(not(feature = "humantime"))]
+    {
+        fmt_duration(dur)
+    }
+}
+
+/// returned by [`check_under!`] when a block ran past its time limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitExceeded {
+    /// how long the block actually took.
+    pub elapsed: Duration,
+    /// the limit it was checked against.
+    pub limit: Duration,
+}
+
+impl fmt::Display for LimitExceeded {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "block took {:?}, expected under {:?}",
+            self.elapsed, self.limit
+        )
+    }
 }
 
+impl Error for LimitExceeded {}
+
 /// use when you need both the result of the block and the time
 /// it took to execute as a tuple.
+///
+/// the block runs inside a closure, so `return` only leaves the block and
+/// `?` needs the block itself to evaluate to a `Result`. see
+/// [`time_block!`] for a form that leaves the enclosing function instead.
 #[macro_export]
 macro_rules! time {
     {$($a:tt)*} => {{
@@ -52,6 +2034,33 @@ macro_rules! time {
     }};
 }
 
+/// like [`time!`], but the block is expanded in place rather than wrapped
+/// in a closure, so `?`, `return` and `break` act on the enclosing function
+/// or loop, in which case no timing is produced.
+///
+/// ```rust
+/// use voxell_timer::time_block;
+///
+/// fn parse_doubled(s: &str) -> Result<i32, core::num::ParseIntError> {
+///     let (n, _dur) = time_block! {
+///         let n: i32 = s.parse()?;
+///         n * 2
+///     };
+///     Ok(n)
+/// }
+///
+/// assert_eq!(parse_doubled("21"), Ok(42));
+/// assert!(parse_doubled("nope").is_err());
+/// ```
+#[macro_export]
+macro_rules! time_block {
+    {$($a:tt)*} => {{
+        let timer = $crate::Timer::start();
+        let res = { $($a)* };
+        (res, timer.stop())
+    }};
+}
+
 /// use for dirty debugging by printing the time it took to execute
 /// the given block.
 ///
@@ -59,14 +2068,34 @@ macro_rules! time {
 ///
 /// or with an unquoted string for some reason: `time_println!(unquoted label, ...)`
 ///
+/// or with a format string and arguments: `time_println!(fmt = "req {} {x}", id, x = 5; ...)`
+///
 /// printing is done to `stdout`
 #[macro_export]
 macro_rules! time_println {
+    // macro time_println(fmt = "format {}", args...; code()...) -> code()::output
+    {fmt = $($rest:tt)*} => {
+        $crate::time_println!(@fmt [] $($rest)*)
+    };
+
+    // the format arguments end at the first top-level `;`
+    {@fmt [$($fmt:tt)*] ; $($b:tt)*} => {{
+        let label = format!($($fmt)*);
+        let f = || { $($b)* };
+        let (res, dur) = $crate::time_fn(f);
+        println!("{}: {}", label, $crate::fmt_elapsed(dur));
+        res
+    }};
+
+    {@fmt [$($fmt:tt)*] $next:tt $($rest:tt)*} => {
+        $crate::time_println!(@fmt [$($fmt)* $next] $($rest)*)
+    };
+
     // macro time_println(unquoted label..., code()... ) -> code()::output
     {$($a:ident)*, $($b:tt)*} => {{
         let f = || { $($b)* };
         let (res, dur) = $crate::time_fn(f);
-        println!("{}: {}ms", stringify!($($a)*), dur.as_millis());
+        println!("{}: {}", stringify!($($a)*), $crate::fmt_elapsed(dur));
         res
     }};
 
@@ -74,7 +2103,7 @@ macro_rules! time_println {
     {$a:expr, $($b:tt)*} => {{
         let f = || { $($b)* };
         let (res, dur) = $crate::time_fn(f);
-        println!("{}: {}ms", $a, dur.as_millis());
+        println!("{}: {}", $a, $crate::fmt_elapsed(dur));
         res
     }};
 
@@ -82,7 +2111,7 @@ macro_rules! time_println {
     {$($a:tt)*} => {{
         let f = || { $($a)* };
         let (res, dur) = $crate::time_fn(f);
-        println!("{}: {}ms", stringify!($($a)*), dur.as_millis());
+        println!("{}: {}", stringify!($($a)*), $crate::fmt_elapsed(dur));
         res
     }};
 }
@@ -94,14 +2123,34 @@ macro_rules! time_println {
 ///
 /// or with an unquoted string for some reason: `time_println!(unquoted label, ...)`
 ///
+/// or with a format string and arguments: `time_println!(fmt = "req {} {x}", id, x = 5; ...)`
+///
 /// printing is done to `stderr`
 #[macro_export]
 macro_rules! time_eprintln {
+    // macro time_println(fmt = "format {}", args...; code()...) -> code()::output
+    {fmt = $($rest:tt)*} => {
+        $crate::time_eprintln!(@fmt [] $($rest)*)
+    };
+
+    // the format arguments end at the first top-level `;`
+    {@fmt [$($fmt:tt)*] ; $($b:tt)*} => {{
+        let label = format!($($fmt)*);
+        let f = || { $($b)* };
+        let (res, dur) = $crate::time_fn(f);
+        eprintln!("{}: {}", label, $crate::fmt_elapsed(dur));
+        res
+    }};
+
+    {@fmt [$($fmt:tt)*] $next:tt $($rest:tt)*} => {
+        $crate::time_eprintln!(@fmt [$($fmt)* $next] $($rest)*)
+    };
+
     // macro time_println(unquoted label..., code()... ) -> code()::output
     {$($a:ident)*, $($b:tt)*} => {{
         let f = || { $($b)* };
         let (res, dur) = $crate::time_fn(f);
-        eprintln!("{}: {}ms", stringify!($($a)*), dur.as_millis());
+        eprintln!("{}: {}", stringify!($($a)*), $crate::fmt_elapsed(dur));
         res
     }};
 
@@ -109,7 +2158,7 @@ macro_rules! time_eprintln {
     {$a:expr, $($b:tt)*} => {{
         let f = || { $($b)* };
         let (res, dur) = $crate::time_fn(f);
-        eprintln!("{}: {}ms", $a, dur.as_millis());
+        eprintln!("{}: {}", $a, $crate::fmt_elapsed(dur));
         res
     }};
 
@@ -117,14 +2166,65 @@ macro_rules! time_eprintln {
     {$($a:tt)*} => {{
         let f = || { $($a)* };
         let (res, dur) = $crate::time_fn(f);
-        eprintln!("{}: {}ms", stringify!($($a)*), dur.as_millis());
+        eprintln!("{}: {}", stringify!($($a)*), $crate::fmt_elapsed(dur));
         res
     }};
 }
+
+/// use to encode a latency expectation inline: times the block and panics
+/// if it took longer than the limit, otherwise evaluates to the block's
+/// value.
+///
+/// ```rust
+/// use core::time::Duration;
+/// use voxell_timer::assert_under;
+///
+/// let res = assert_under!(Duration::from_secs(10), {
+///     5 + 3
+/// });
+/// assert_eq!(res, 8);
+/// ```
+#[macro_export]
+macro_rules! assert_under {
+    ($limit:expr, $($b:tt)*) => {{
+        match $crate::check_under!($limit, $($b)*) {
+            ::core::result::Result::Ok(res) => res,
+            ::core::result::Result::Err(err) => panic!("{}", err),
+        }
+    }};
+}
+
+/// non-panicking [`assert_under!`]: times the block and evaluates to
+/// `Ok(value)` if it finished within the limit, or `Err(LimitExceeded)`
+/// otherwise. the block's value is dropped on the error path.
+///
+/// ```rust
+/// use core::time::Duration;
+/// use voxell_timer::check_under;
+///
+/// let res = check_under!(Duration::ZERO, {
+///     std::thread::sleep(Duration::from_millis(1));
+/// });
+/// assert!(res.is_err());
+/// ```
+#[macro_export]
+macro_rules! check_under {
+    ($limit:expr, $($b:tt)*) => {{
+        let limit: ::core::time::Duration = $limit;
+        let (res, elapsed) = $crate::time! { $($b)* };
+        if elapsed > limit {
+            ::core::result::Result::Err($crate::LimitExceeded { elapsed, limit })
+        } else {
+            ::core::result::Result::Ok(res)
+        }
+    }};
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use core::iter;
+    use core::num::ParseIntError;
 
     #[test]
     fn works() {
@@ -145,6 +2245,435 @@ mod tests {
         assert_eq!(res, Noncopy);
     }
 
+    #[test]
+    fn system_clock_saturates() {
+        let now = SystemTime::now();
+        let later = now + Duration::from_secs(1);
+        assert_eq!(SystemClock.between(later, now), Duration::ZERO);
+        assert_eq!(SystemClock.between(now, later), Duration::from_secs(1));
+
+        let (res, _dur) = time_fn_with_clock(&MonotonicClock, || 5 + 3);
+        assert_eq!(res, 8);
+    }
+
+    #[test]
+    fn with_state() {
+        let (state, res, _dur) = time_fn_with_state(vec![1, 2], |mut v| {
+            v.push(3);
+            let len = v.len();
+            (v, len)
+        });
+        assert_eq!(state, [1, 2, 3]);
+        assert_eq!(res, 3);
+    }
+
+    #[test]
+    fn repeated_mutation() {
+        let mut buf = Vec::new();
+        let mut push = || buf.push(buf.len());
+        let durs = time_fn_mut(4, &mut push);
+        assert_eq!(durs.len(), 4);
+        assert_eq!(buf, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn loop_iterations() {
+        let (iterations, _total, per_iteration) = time_loop(|| false);
+        assert_eq!(iterations, 0);
+        assert_eq!(per_iteration, Duration::ZERO);
+
+        let mut n = 0;
+        let (iterations, total, per_iteration) = time_loop(|| {
+            n += 1;
+            n <= 3
+        });
+        assert_eq!(iterations, 3);
+        assert!(per_iteration <= total, "{per_iteration:?} > {total:?}");
+    }
+
+    #[test]
+    fn warm_runs_twice() {
+        let mut calls = 0;
+        let (res, _cold, _warm) = time_fn_warm(&mut || {
+            calls += 1;
+            calls
+        });
+        assert_eq!(res, 2);
+    }
+
+    /// whether `thread_cpu_time` can read real per-thread CPU time here.
+    const HAS_THREAD_CPU_TIME: bool = cfg!(all(
+        feature = "cpu-time",
+        any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "freebsd"
+        )
+    ));
+
+    #[test]
+    fn wall_vs_cpu() {
+        let ((), wall, cpu) = time_fn_both(|| thread::sleep(Duration::from_millis(20)));
+        assert!(wall >= Duration::from_millis(20), "slept for 20ms");
+        if HAS_THREAD_CPU_TIME {
+            assert!(cpu < wall, "sleeping must not burn CPU time");
+        } else {
+            assert_eq!(cpu, wall);
+        }
+    }
+
+    #[test]
+    fn threaded_println() {
+        let res = thread::Builder::new()
+            .name("worker-2".to_owned())
+            .spawn(|| {
+                assert_eq!(thread_prefix(&thread::current()), "[worker-2]");
+                time_fn_println_threaded("threaded", || 5 + 3)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(res, 8);
+
+        thread::spawn(|| {
+            let current = thread::current();
+            assert_eq!(current.name(), None);
+            assert_eq!(thread_prefix(&current), format!("[{:?}]", current.id()));
+            assert!(thread_prefix(&current).starts_with("[ThreadId("));
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn parallel_race() {
+        let (results, winner) = time_all_parallel(vec![|| 1, || 2, || 3]);
+        assert_eq!(
+            results.iter().map(|&(r, _)| r).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        assert!(
+            winner.is_some_and(|i| i < 3),
+            "winner must index into the input"
+        );
+
+        let (results, winner) = time_all_parallel(Vec::<fn() -> u8>::new());
+        assert!(results.is_empty());
+        assert_eq!(winner, None);
+    }
+
+    #[test]
+    fn stopwatch() {
+        let timer = Timer::start();
+        let peek = timer.elapsed();
+        let total = timer.stop();
+        assert!(total >= peek, "stopping must not go back in time");
+    }
+
+    #[test]
+    fn human_readable_durations() {
+        let (_, dur) = time_fn(|| 5 + 3);
+        assert_ne!(timing_line("trivial", dur), "trivial: 0ms");
+        assert_ne!(
+            timing_line("trivial", Duration::from_nanos(250)),
+            "trivial: 0ms"
+        );
+
+        let cases = [
+            (Duration::from_nanos(7), "7ns"),
+            (Duration::from_nanos(999), "999ns"),
+            (Duration::from_micros(1), "1µs"),
+            (Duration::from_nanos(12_345), "12.3µs"),
+            (Duration::from_nanos(999_500), "1ms"),
+            (Duration::from_millis(45_600), "45.6s"),
+            (Duration::from_secs(90), "1.5m"),
+            (Duration::from_hours(2), "2h"),
+            (Duration::from_secs(1234), "20.6m"),
+            (Duration::from_millis(59_960), "1m"),
+            (Duration::from_secs(3_599), "1h"),
+            (Duration::from_mins(74_070), "1230h"),
+            (Duration::MAX, "5120000000000000h"),
+        ];
+        for (dur, expected) in cases {
+            assert_eq!(fmt_duration(dur), expected, "{dur:?}");
+        }
+    }
+
+    #[test]
+    fn format_config_bounds() {
+        let never_hours = FormatConfig {
+            max_unit: TimeUnit::Mins,
+            ..FormatConfig::default()
+        };
+        assert_eq!(never_hours.format(Duration::from_hours(2)), "120m");
+        assert_eq!(never_hours.format(Duration::from_secs(1234)), "20.6m");
+        assert_eq!(never_hours.format(Duration::from_mins(74_070)), "74100m");
+
+        let hours = FormatConfig {
+            min_unit: TimeUnit::Hours,
+            significant_figures: 4,
+            ..FormatConfig::default()
+        };
+        assert_eq!(hours.format(Duration::from_secs(1234)), "0.3428h");
+        assert_eq!(hours.format(Duration::from_mins(74_070)), "1235h");
+
+        let minutes = FormatConfig {
+            min_unit: TimeUnit::Mins,
+            max_unit: TimeUnit::Mins,
+            significant_figures: 2,
+            ..FormatConfig::default()
+        };
+        assert_eq!(minutes.format(Duration::from_secs(1234)), "21m");
+        assert_eq!(minutes.format(Duration::from_secs(1_000)), "17m");
+
+        let whole_ms = FormatConfig {
+            min_unit: TimeUnit::Millis,
+            max_unit: TimeUnit::Millis,
+            significant_figures: 1,
+            ..FormatConfig::default()
+        };
+        assert_eq!(whole_ms.format(Duration::from_micros(40)), "0.04ms");
+        assert_eq!(whole_ms.format(Duration::from_millis(1_234)), "1000ms");
+        assert_eq!(whole_ms.format(Duration::ZERO), "0ms");
+
+        let inverted = FormatConfig {
+            min_unit: TimeUnit::Secs,
+            max_unit: TimeUnit::Nanos,
+            significant_figures: 0,
+            ..FormatConfig::default()
+        };
+        assert_eq!(inverted.format(Duration::from_millis(20)), "0.02s");
+
+        let huge = FormatConfig {
+            significant_figures: u32::MAX,
+            ..FormatConfig::default()
+        };
+        assert_eq!(huge.format(Duration::from_millis(1_500)), "1.5s");
+        assert_eq!(
+            huge.format(Duration::from_nanos(1_234_567_891)),
+            "1.234567891s"
+        );
+        assert_eq!(huge.format(Duration::from_nanos(7)), "7ns");
+    }
+
+    #[test]
+    fn format_config_rounding() {
+        let with = |rounding| FormatConfig {
+            rounding,
+            ..FormatConfig::default()
+        };
+        let cases = [
+            (1_239, "1.23µs", "1.24µs", "1.24µs"),
+            (1_235, "1.23µs", "1.24µs", "1.24µs"),
+            (1_225, "1.22µs", "1.23µs", "1.22µs"),
+            (59_999_000_000, "59.9s", "1m", "1m"),
+        ];
+        for (nanos, truncate, half_up, half_even) in cases {
+            let dur = Duration::from_nanos(nanos);
+            assert_eq!(with(RoundingMode::Truncate).format(dur), truncate);
+            assert_eq!(with(RoundingMode::HalfUp).format(dur), half_up);
+            assert_eq!(with(RoundingMode::HalfEven).format(dur), half_even);
+        }
+        assert_eq!(FormatConfig::default().rounding, RoundingMode::HalfUp);
+    }
+
+    #[cfg(not(feature = "humantime"))]
+    #[test]
+    fn flat_report_table() {
+        let report = [
+            ("parse".to_owned(), Duration::from_millis(3)),
+            ("µ-lex".to_owned(), Duration::from_micros(1500)),
+        ]
+        .into_iter()
+        .collect::<FlatReport>();
+        assert_eq!(
+            report.to_pretty(),
+            "name   duration\n\
+             parse       3ms\n\
+             µ-lex     1.5ms\n"
+        );
+        assert_eq!(FlatReport::default().to_pretty(), "name  duration\n");
+    }
+
+    #[test]
+    fn timing_accumulator() {
+        let mut acc = TimingAccumulator::new();
+        assert_eq!(acc.mean(), Duration::ZERO);
+        assert_eq!(acc.min(), Duration::ZERO);
+
+        for ms in [3, 1, 2] {
+            acc.record(Duration::from_millis(ms));
+        }
+        assert_eq!(acc.count(), 3);
+        assert_eq!(acc.total(), Duration::from_millis(6));
+        assert_eq!(acc.min(), Duration::from_millis(1));
+        assert_eq!(acc.max(), Duration::from_millis(3));
+        assert_eq!(acc.mean(), Duration::from_millis(2));
+    }
+
+    #[test]
+    fn time_into_is_transparent() {
+        let mut acc = TimingAccumulator::new();
+        let items = ["a", "b"]
+            .into_iter()
+            .time_into(&mut acc)
+            .collect::<Vec<_>>();
+        assert_eq!(items, ["a", "b"]);
+        assert_eq!(acc.count(), 2);
+    }
+
+    #[test]
+    fn ewma_smoothing() {
+        let mut ewma = Ewma::new(0.25);
+        assert_eq!(ewma.value(), Duration::ZERO);
+        ewma.record(Duration::from_millis(100));
+        assert_eq!(ewma.value(), Duration::from_millis(100));
+        ewma.record(Duration::from_millis(200));
+        assert_eq!(ewma.value(), Duration::from_millis(125));
+
+        let mut latest = Ewma::new(7.0);
+        latest.record(Duration::from_millis(1));
+        latest.record(Duration::from_millis(3));
+        assert_eq!(latest.value(), Duration::from_millis(3));
+
+        let mut nan = Ewma::new(f64::NAN);
+        nan.record(Duration::from_millis(1));
+        nan.record(Duration::from_millis(3));
+        assert_eq!(nan.value(), Duration::from_millis(3));
+    }
+
+    #[test]
+    fn probes() {
+        let mut probes = Probes::new();
+        assert_eq!(probes.stop("never started"), None);
+
+        probes.start("a");
+        probes.start("b");
+        assert!(probes.stop("a").is_some(), "a was started");
+        probes.start("a");
+        assert!(probes.stop("a").is_some(), "a was restarted");
+
+        let report = probes.report();
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].0, &"a");
+        assert_eq!(report[0].2, 2);
+
+        let mut owned = Probes::new();
+        owned.start(format!("req {}", 1));
+        assert!(owned.stop("req 1").is_some(), "String keys stop by &str");
+    }
+
+    #[test]
+    fn bench_stats() {
+        let mut samples = [4, 1, 3, 2].map(Duration::from_millis);
+        let stats = BenchStats::from_samples(&mut samples);
+        assert_eq!(stats.runs, 4);
+        assert_eq!(stats.min, Duration::from_millis(1));
+        assert_eq!(stats.max, Duration::from_millis(4));
+        assert_eq!(stats.total, Duration::from_millis(10));
+        assert_eq!(stats.mean, Duration::from_micros(2500));
+        assert_eq!(stats.median, Duration::from_micros(2500));
+
+        assert_eq!(BenchStats::from_samples(&mut []), BenchStats::default());
+    }
+
+    #[test]
+    fn bench_stats_combine() {
+        let a = BenchStats::from_samples(&mut [1, 3].map(Duration::from_millis));
+        let b = BenchStats::from_samples(&mut [2, 6, 10].map(Duration::from_millis));
+        let all = a.combine(&b);
+        assert_eq!(all.runs, 5);
+        assert_eq!(all.total, Duration::from_millis(22));
+        assert_eq!(all.min, Duration::from_millis(1));
+        assert_eq!(all.max, Duration::from_millis(10));
+        assert_eq!(all.mean, Duration::from_micros(4400));
+        // (2ms * 2 + 6ms * 3) / 5
+        assert_eq!(all.median, Duration::from_micros(4400));
+
+        assert_eq!(a.combine(&BenchStats::default()), a);
+        assert_eq!(BenchStats::default().combine(&a), a);
+    }
+
+    #[test]
+    fn baseline_regressions() {
+        let ms = Duration::from_millis;
+        let baseline = [(1, ms(10), 1), (2, ms(10), 1), (3, ms(10), 1)];
+        let current = [(1, ms(10), 1), (2, ms(11), 4), (4, ms(50), 1)];
+
+        assert!(compare_to_baseline(&current, &baseline, 0.2).is_empty());
+        let strict = compare_to_baseline(&current, &baseline, 0.0);
+        assert_eq!(
+            strict,
+            [Regression {
+                ident: 2,
+                baseline: ms(10),
+                current: ms(11),
+            }]
+        );
+    }
+
+    #[test]
+    fn baseline_significance() {
+        let ms = Duration::from_millis;
+        let baseline = [(1, ms(10), 1), (2, ms(100), 1)];
+        let current = [(1, ms(12), 1), (2, ms(150), 1)];
+
+        let regressions = compare_to_baseline_with_significance(&current, &baseline, 0.1, ms(5));
+        assert_eq!(regressions.len(), 1);
+        assert_eq!(regressions[0].ident, 2);
+        assert_eq!(
+            compare_to_baseline_with_significance(&current, &baseline, 0.1, ms(2)).len(),
+            1,
+            "a delta equal to the threshold is not significant"
+        );
+        assert_eq!(
+            compare_to_baseline_with_significance(&current, &baseline, 0.1, Duration::ZERO),
+            compare_to_baseline(&current, &baseline, 0.1)
+        );
+    }
+
+    #[test]
+    fn bench_csv_rows() {
+        let stats = BenchStats::from_samples(&mut [1, 3].map(Duration::from_nanos));
+        let mut csv = BenchCsvWriter::new(Vec::new());
+        csv.write_run("plain", &stats).unwrap();
+        csv.write_run("n=1, \"quoted\"", &stats).unwrap();
+        assert_eq!(
+            String::from_utf8(csv.into_inner()).unwrap(),
+            "plain,2,4,1,3,2,2\n\
+             \"n=1, \"\"quoted\"\"\",2,4,1,3,2,2\n"
+        );
+    }
+
+    #[test]
+    fn near_resolution() {
+        let stats = BenchStats {
+            median: Duration::from_nanos(500),
+            ..BenchStats::default()
+        };
+        assert!(stats.is_near_resolution(Duration::from_nanos(100), RESOLUTION_WARN_FACTOR));
+        assert!(!stats.is_near_resolution(Duration::from_nanos(10), RESOLUTION_WARN_FACTOR));
+        assert!(!stats.is_near_resolution(Duration::from_nanos(100), 2));
+    }
+
+    #[test]
+    fn speedup_wording() {
+        let with_mean = |ms| BenchStats {
+            mean: Duration::from_millis(ms),
+            ..BenchStats::default()
+        };
+        assert_eq!(
+            speedup_line("a", &with_mean(4), "b", &with_mean(2)),
+            "b is 2.00x faster than a"
+        );
+        assert_eq!(
+            speedup_line("a", &with_mean(2), "b", &with_mean(4)),
+            "b is 2.00x slower than a"
+        );
+    }
+
     #[test]
     fn time_macro() {
         let (res, _dur) = time! {3 + 5};
@@ -199,6 +2728,175 @@ mod tests {
         assert_eq!(res, 8);
     }
 
+    #[test]
+    fn time_formatted_println_macro() {
+        let (id, phase) = (7, "parse");
+        let res = time_println! {
+            fmt = "req {} phase {}", id, phase;
+            3 + 5
+        };
+        assert_eq!(res, 8);
+    }
+
+    #[test]
+    fn time_formatted_eprintln_macro() {
+        let id = 7;
+        let res = time_eprintln! {
+            fmt = "req {id}";
+            let x = 3;
+            x + 5
+        };
+        assert_eq!(res, 8);
+    }
+
+    #[test]
+    fn time_formatted_named_args() {
+        let phase = "lex";
+        let res = time_println! {
+            fmt = "req {x} phase {phase} {:>3}", 1, x = [5, 6].len();
+            let x = 3;
+            x + 5
+        };
+        assert_eq!(res, 8);
+
+        let res = time_eprintln!(fmt = "req {x}", x = 5; 1 + 1);
+        assert_eq!(res, 2);
+    }
+
+    #[cfg(feature = "alloc-stats")]
+    #[global_allocator]
+    static GLOBAL: CountingAlloc = CountingAlloc::system();
+
+    #[cfg(feature = "alloc-stats")]
+    #[test]
+    fn counts_allocations() {
+        let (v, _dur, stats) = time_and_alloc(|| Vec::<u8>::with_capacity(64));
+        assert_eq!(v.capacity(), 64);
+        assert_eq!(stats.allocations, 1);
+        assert_eq!(stats.deallocations, 0);
+        assert_eq!(stats.bytes_allocated, 64);
+    }
+
+    #[test]
+    fn nested_macros_share_captures() {
+        let data = (1..=3).collect::<Vec<i32>>();
+        let mut calls = 0;
+        let f = 10;
+        let ((sum, _inner), _outer) = time! {
+            calls += 1;
+            time! {
+                calls += 1;
+                time_eprintln! {
+                    "innermost",
+                    calls += 1;
+                    data.iter().sum::<i32>() + f
+                }
+            }
+        };
+        assert_eq!(sum, 16);
+        assert_eq!(calls, 3);
+        assert_eq!(data.len(), 3);
+    }
+
+    #[test]
+    fn nested_macros_move_and_early_return() {
+        fn parse_doubled(s: &str) -> Result<(i32, Duration), ParseIntError> {
+            let owned = s.to_owned();
+            let (n, outer) = time_block! {
+                let (n, _inner) = time_block! {
+                    let moved = owned;
+                    let n: i32 = moved.trim().parse()?;
+                    n * 2
+                };
+                n
+            };
+            Ok((n, outer))
+        }
+
+        assert_eq!(parse_doubled(" 21 ").map(|(n, _)| n), Ok(42));
+        assert!(parse_doubled("nope").is_err(), "? must leave the function");
+    }
+
+    #[test]
+    fn time_return_stays_in_block() {
+        fn early(c: bool) -> i32 {
+            let (x, _): (i32, _) = time! {
+                if c {
+                    return 1;
+                }
+                2
+            };
+            x.saturating_add(10)
+        }
+
+        assert_eq!(early(true), 11);
+        assert_eq!(early(false), 12);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn poll_time_skips_parked_time() {
+        use core::pin::pin;
+        use core::task::Waker;
+
+        struct YieldOnce(bool);
+        impl Future for YieldOnce {
+            type Output = u8;
+            fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<u8> {
+                if self.0 {
+                    Poll::Ready(8)
+                } else {
+                    self.0 = true;
+                    Poll::Pending
+                }
+            }
+        }
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut fut = pin!(time_poll(YieldOnce(false)));
+        assert!(fut.as_mut().poll(&mut cx).is_pending(), "first poll yields");
+
+        let parked = Timer::start();
+        thread::sleep(Duration::from_millis(20));
+        let parked = parked.stop();
+
+        let Poll::Ready((res, busy)) = fut.as_mut().poll(&mut cx) else {
+            panic!("second poll must finish");
+        };
+        assert_eq!(res, 8);
+        assert!(busy < parked, "parked time must not be counted");
+    }
+
+    #[test]
+    fn under_limit() {
+        let res = assert_under!(Duration::from_secs(10), 3 + 5);
+        assert_eq!(res, 8);
+
+        let err = check_under!(Duration::ZERO, {
+            thread::sleep(Duration::from_millis(1));
+        })
+        .unwrap_err();
+        assert_eq!(err.limit, Duration::ZERO);
+        assert!(err.to_string().starts_with("block took "), "{err}");
+    }
+
+    #[test]
+    #[should_panic(expected = "expected under 0ns")]
+    fn over_limit_panics() {
+        assert_under!(Duration::ZERO, thread::sleep(Duration::from_millis(1)));
+    }
+
+    #[cfg(all(feature = "tsc", target_arch = "x86_64"))]
+    #[test]
+    fn tsc_roundtrip() {
+        let hz = tsc_hz_estimate(Duration::from_millis(10));
+        assert!(hz > 0, "the TSC must tick");
+
+        let (res, ticks) = time_fn_tsc(|| 5 + 3);
+        assert_eq!(res, 8);
+        assert!(cycles_to_duration(ticks, hz) < Duration::from_secs(1));
+    }
+
     #[test]
     fn extensive_test() {
         fn xorshift32(inp: &mut u32) -> u32 {